@@ -0,0 +1,250 @@
+use crate::log::{self, COUNTERS};
+use crate::point::Point;
+use clap::ValueEnum;
+use influxdb::{Client, Query, QueryType, ValidQuery};
+use serde::Deserialize;
+
+/// Which InfluxDB HTTP API to write to
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Api {
+    /// InfluxDB 1.x: /write with user, password and database
+    V1,
+    /// InfluxDB 2.x and 3.x: /api/v2/write with token, org and bucket
+    V2,
+}
+
+/// Timestamp precision of the points sent with the v2 API
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Precision {
+    Ns,
+    Us,
+    Ms,
+    S,
+}
+
+impl Precision {
+    fn as_str(self) -> &'static str {
+        match self {
+            Precision::Ns => "ns",
+            Precision::Us => "us",
+            Precision::Ms => "ms",
+            Precision::S => "s",
+        }
+    }
+
+    fn divisor(self) -> u128 {
+        match self {
+            Precision::Ns => 1,
+            Precision::Us => 1_000,
+            Precision::Ms => 1_000_000,
+            Precision::S => 1_000_000_000,
+        }
+    }
+}
+
+/// Writes to the InfluxDB 2.x `/api/v2/write` endpoint, that InfluxDB 3.x also
+/// accepts.
+pub struct V2Client {
+    http: reqwest::Client,
+    url: String,
+    org: String,
+    bucket: String,
+    token: String,
+    precision: Precision,
+}
+
+impl V2Client {
+    pub fn new(
+        http: reqwest::Client,
+        host: &str,
+        org: String,
+        bucket: String,
+        token: String,
+        precision: Precision,
+    ) -> Self {
+        V2Client {
+            http,
+            url: format!("{}/api/v2/write", host.trim_end_matches('/')),
+            org,
+            bucket,
+            token,
+            precision,
+        }
+    }
+
+    async fn send(&self, body: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let res = self
+            .http
+            .post(&self.url)
+            .query(&[
+                ("org", self.org.as_str()),
+                ("bucket", self.bucket.as_str()),
+                ("precision", self.precision.as_str()),
+            ])
+            .header("Authorization", format!("Token {}", self.token))
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(body.to_string())
+            .send()
+            .await?;
+        let status = res.status();
+        if !status.is_success() {
+            let text = res.text().await.unwrap_or_default();
+            return Err(format!("InfluxDB v2 write failed ({}): {}", status, text).into());
+        }
+        Ok(())
+    }
+}
+
+// Points are always built with nanosecond timestamps, scale the trailing
+// timestamp of a point to the requested precision.
+fn with_precision(line: &str, precision: Precision) -> String {
+    if precision == Precision::Ns {
+        return line.to_string();
+    }
+    match line.rsplit_once(' ') {
+        Some((point, ts)) => match ts.parse::<u128>() {
+            Ok(ts) => format!("{} {}", point, ts / precision.divisor()),
+            Err(_) => line.to_string(),
+        },
+        None => line.to_string(),
+    }
+}
+
+// Line protocol that has already been built, e.g. read back from the spool
+struct Lines<'a>(&'a str);
+
+impl Query for Lines<'_> {
+    fn build(&self) -> Result<ValidQuery, influxdb::Error> {
+        Ok(ValidQuery::from(self.0))
+    }
+
+    fn build_with_opts(&self, _use_v2: bool) -> Result<ValidQuery, influxdb::Error> {
+        self.build()
+    }
+
+    fn get_type(&self) -> QueryType {
+        QueryType::WriteQuery("ns".to_string())
+    }
+}
+
+pub enum Backend {
+    V1(Client),
+    V2(V2Client),
+}
+
+impl Backend {
+    /// Sends lines built with unsigned integer support and nanosecond
+    /// timestamps, converted to what this backend expects
+    pub async fn send(
+        &self,
+        lines: &[String],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match self {
+            Backend::V1(client) => match client.query(Lines(&v1_lines(lines))).await {
+                Ok(_) => Ok(()),
+                Err(e) => Err(redact_password(&e.to_string()).into()),
+            },
+            Backend::V2(client) => {
+                let lines: Vec<String> = lines
+                    .iter()
+                    .map(|line| with_precision(line, client.precision))
+                    .collect();
+                client.send(&lines.join("\n")).await
+            }
+        }
+    }
+}
+
+// InfluxDB 1.x doesn't know unsigned integers, they are written as `1i`
+// instead of `1u`. A line that can't be read back is dropped.
+fn v1_lines(lines: &[String]) -> String {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let built = Point::parse(line).and_then(|point| {
+                let query = point.into_query();
+                query.build().map(|q| q.get()).map_err(|e| e.to_string())
+            });
+            match built {
+                Ok(line) => Some(line),
+                Err(e) => {
+                    eprintln!("Dropping invalid point ({}): {}", e, line);
+                    log::count(&COUNTERS.points_dropped, 1);
+                    None
+                }
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// The v1 API takes the password in the URL, that ends up in connection errors
+fn redact_password(message: &str) -> String {
+    let mut redacted = String::new();
+    let mut rest = message;
+    while let Some(start) = rest.find("p=").filter(|&i| i > 0) {
+        let before = &rest[..start];
+        redacted.push_str(before);
+        redacted.push_str("p=");
+        rest = &rest[start + 2..];
+        if before.ends_with('?') || before.ends_with('&') {
+            redacted.push_str("[REDACTED]");
+            let end = rest.find(['&', ')', ' ']).unwrap_or(rest.len());
+            rest = &rest[end..];
+        }
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "m,host=vm v=1u 1700000000123456789";
+
+    #[test]
+    fn keeps_nanoseconds() {
+        assert_eq!(with_precision(LINE, Precision::Ns), LINE);
+    }
+
+    #[test]
+    fn truncates_timestamps_to_the_precision() {
+        assert_eq!(
+            with_precision(LINE, Precision::Us),
+            "m,host=vm v=1u 1700000000123456"
+        );
+        assert_eq!(
+            with_precision(LINE, Precision::Ms),
+            "m,host=vm v=1u 1700000000123"
+        );
+        assert_eq!(
+            with_precision(LINE, Precision::S),
+            "m,host=vm v=1u 1700000000"
+        );
+    }
+
+    #[test]
+    fn leaves_lines_without_timestamp() {
+        assert_eq!(with_precision("m v=1i", Precision::S), "m v=1i");
+    }
+
+    #[test]
+    fn only_scales_the_timestamp_of_a_multi_line_field() {
+        assert_eq!(
+            with_precision("m s=\"a 12\nb 34\" 1700000000999999999", Precision::S),
+            "m s=\"a 12\nb 34\" 1700000000"
+        );
+    }
+
+    #[test]
+    fn converts_unsigned_integers_for_v1() {
+        let lines = vec![
+            "m,host=vm u=1u,i=2i 1700000000123456789".to_string(),
+            "invalid".to_string(),
+        ];
+        assert_eq!(v1_lines(&lines), "m,host=vm u=1i,i=2i 1700000000123456789");
+    }
+}
@@ -0,0 +1,171 @@
+use crate::backend::{Api, Precision};
+use crate::schema::Schema;
+use crate::tls::Pin;
+use crate::Cli;
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Settings that can be given in the config file, at the top level or in a
+/// profile. Names are the same as the command line options.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Settings {
+    api: Option<Api>,
+    host: Option<String>,
+    user: Option<String>,
+    password: Option<String>,
+    password_file: Option<PathBuf>,
+    password_command: Option<String>,
+    database: Option<String>,
+    token: Option<String>,
+    org: Option<String>,
+    bucket: Option<String>,
+    precision: Option<Precision>,
+    ca_cert: Option<PathBuf>,
+    client_cert: Option<PathBuf>,
+    client_key: Option<PathBuf>,
+    insecure_skip_verify: Option<bool>,
+    pin_sha256: Option<Vec<Pin>>,
+    hostname: Option<String>,
+    repository: Option<String>,
+    job: Option<String>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+    schema: Option<Schema>,
+}
+
+impl Settings {
+    // Values of the profile replace the top level ones, tags are merged
+    fn merge(self, profile: Settings) -> Settings {
+        let mut tags = self.tags;
+        tags.extend(profile.tags);
+        Settings {
+            api: profile.api.or(self.api),
+            host: profile.host.or(self.host),
+            user: profile.user.or(self.user),
+            password: profile.password.or(self.password),
+            password_file: profile.password_file.or(self.password_file),
+            password_command: profile.password_command.or(self.password_command),
+            database: profile.database.or(self.database),
+            token: profile.token.or(self.token),
+            org: profile.org.or(self.org),
+            bucket: profile.bucket.or(self.bucket),
+            precision: profile.precision.or(self.precision),
+            ca_cert: profile.ca_cert.or(self.ca_cert),
+            client_cert: profile.client_cert.or(self.client_cert),
+            client_key: profile.client_key.or(self.client_key),
+            insecure_skip_verify: profile.insecure_skip_verify.or(self.insecure_skip_verify),
+            pin_sha256: profile.pin_sha256.or(self.pin_sha256),
+            hostname: profile.hostname.or(self.hostname),
+            repository: profile.repository.or(self.repository),
+            job: profile.job.or(self.job),
+            tags,
+            schema: profile.schema.or(self.schema),
+        }
+    }
+}
+
+/// Default location of the config file, in the user's config directory
+pub fn default_path() -> Option<PathBuf> {
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("restic-to-influxdb").join("config.toml"))
+}
+
+// Reads the top level settings and the given profile
+fn load(path: &Path, profile: Option<&str>) -> Result<Settings, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut table: toml::Table = text
+        .parse()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut profiles = match table.remove("profiles") {
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(_) => return Err(format!("{}: profiles must be a table", path.display())),
+        None => toml::Table::new(),
+    };
+    let parse = |table: toml::Table| {
+        Settings::deserialize(table).map_err(|e| format!("{}: {}", path.display(), e))
+    };
+    let settings = parse(table)?;
+    match profile {
+        None => Ok(settings),
+        Some(name) => match profiles.remove(name) {
+            Some(toml::Value::Table(profile)) => Ok(settings.merge(parse(profile)?)),
+            _ => Err(format!("{}: no profile `{}`", path.display(), name)),
+        },
+    }
+}
+
+// Whether the option was left to its default, so that the config file applies
+fn unset(matches: &ArgMatches, id: &str) -> bool {
+    !matches!(
+        matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+/// Fills the options that weren't given on the command line or in the
+/// environment from the config file. Without `--config`, a missing default
+/// config file is not an error.
+pub fn apply(cli: &mut Cli, matches: &ArgMatches) -> Result<(), String> {
+    let path = match cli.config.clone().or_else(default_path) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    if cli.config.is_none() && !path.exists() {
+        return match &cli.profile {
+            Some(profile) => Err(format!(
+                "no config file at {} for profile `{}`",
+                path.display(),
+                profile
+            )),
+            None => Ok(()),
+        };
+    }
+    let settings = load(&path, cli.profile.as_deref())?;
+
+    macro_rules! fill {
+        ($($field:ident),*) => {
+            $(
+                if unset(matches, stringify!($field)) {
+                    if let Some(value) = settings.$field {
+                        cli.$field = value.into();
+                    }
+                }
+            )*
+        };
+    }
+    fill!(api, host, user, database, token, org, bucket, precision, hostname, repository, job);
+    fill!(ca_cert, client_cert, client_key, insecure_skip_verify);
+    fill!(pin_sha256);
+
+    // the password sources replace each other: one given on the command line
+    // or in the environment hides all those of the config file
+    if ["password", "password_file", "password_command"]
+        .iter()
+        .all(|id| unset(matches, id))
+    {
+        fill!(password, password_file, password_command);
+    }
+
+    if let Some(schema) = settings.schema {
+        cli.schema = schema;
+    }
+
+    // tags given with --tag win over the ones of the config file
+    let mut tags: Vec<(String, String)> = settings
+        .tags
+        .into_iter()
+        .filter(|(key, _)| !cli.tag.iter().any(|(tag, _)| tag == key))
+        .collect();
+    tags.append(&mut cli.tag);
+    cli.tag = tags;
+
+    Ok(())
+}
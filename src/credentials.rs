@@ -0,0 +1,36 @@
+use crate::Cli;
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use std::fs;
+use std::process::{Command, Stdio};
+
+/// Reads the password from `--password-file` or `--password-command`, which
+/// win over `$INFLUXDB_PASSWORD`. Like restic, the trailing newline is dropped.
+pub fn resolve(cli: &mut Cli, matches: &ArgMatches) -> Result<(), String> {
+    // not a clap conflict, that would also reject the environment variable
+    if (cli.password_file.is_some() || cli.password_command.is_some())
+        && matches.value_source("password") == Some(ValueSource::CommandLine)
+    {
+        return Err("--password can't be used with --password-file or --password-command".into());
+    }
+    let password = if let Some(path) = &cli.password_file {
+        fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?
+    } else if let Some(command) = &cli.password_command {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| format!("could not run the password command: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("the password command failed: {}", output.status));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|_| "the password command printed invalid UTF-8".to_string())?
+    } else {
+        return Ok(());
+    };
+    cli.password = Some(password.trim_end_matches(['\n', '\r']).to_string());
+    Ok(())
+}
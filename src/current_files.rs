@@ -0,0 +1,122 @@
+use clap::ValueEnum;
+
+/// What is kept of the files restic is working on, on each status point
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CurrentFiles {
+    /// The paths
+    Full,
+    /// Nothing
+    Drop,
+    /// Only their number, as current_files_count
+    Count,
+    /// A hash of each path, to tell files apart without storing their names
+    Hash,
+    /// The first component of each path
+    TopLevel,
+}
+
+impl CurrentFiles {
+    /// The current_files field, and current_files_count in count mode
+    pub fn render(
+        self,
+        files: &[String],
+        max_length: Option<usize>,
+    ) -> (Option<String>, Option<u64>) {
+        let files: Vec<String> = match self {
+            CurrentFiles::Drop => return (None, None),
+            CurrentFiles::Count => return (None, Some(files.len() as u64)),
+            CurrentFiles::Full => files.to_vec(),
+            CurrentFiles::Hash => files
+                .iter()
+                .map(|file| format!("{:016x}", fnv1a(file)))
+                .collect(),
+            CurrentFiles::TopLevel => {
+                let mut top: Vec<String> = files.iter().map(|file| top_level(file)).collect();
+                top.dedup();
+                top
+            }
+        };
+        let mut joined = join(&files);
+        if let Some(max_length) = max_length {
+            if let Some((end, _)) = joined.char_indices().nth(max_length) {
+                joined.truncate(end);
+                // don't leave half of an escape
+                let backslashes = joined.chars().rev().take_while(|c| *c == '\\').count();
+                if backslashes % 2 == 1 {
+                    joined.pop();
+                }
+            }
+        }
+        (Some(joined), None)
+    }
+}
+
+// Commas in the paths are escaped as `\,`, and backslashes as `\\`, so that
+// the list can be split back
+fn join(files: &[String]) -> String {
+    files
+        .iter()
+        .map(|file| file.replace('\\', "\\\\").replace(',', "\\,"))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn top_level(path: &str) -> String {
+    let root = if path.starts_with('/') { "/" } else { "" };
+    let first = path
+        .split('/')
+        .find(|part| !part.is_empty())
+        .unwrap_or_default();
+    format!("{}{}", root, first)
+}
+
+// 64-bit FNV-1a, stable across versions and platforms unlike the std hasher.
+// Not cryptographic: known paths can still be recognized.
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full(files: &[&str], max_length: Option<usize>) -> String {
+        let files: Vec<String> = files.iter().map(|file| file.to_string()).collect();
+        CurrentFiles::Full.render(&files, max_length).0.unwrap()
+    }
+
+    #[test]
+    fn escapes_commas_and_backslashes() {
+        assert_eq!(full(&["/a,b", "/c\\d", "/e"], None), "/a\\,b,/c\\\\d,/e");
+    }
+
+    #[test]
+    fn doesnt_cut_an_escaped_comma() {
+        assert_eq!(full(&["a,b"], Some(2)), "a");
+        assert_eq!(full(&["a,b"], Some(3)), "a\\,");
+    }
+
+    #[test]
+    fn doesnt_cut_an_escaped_backslash() {
+        assert_eq!(full(&["a\\b"], Some(2)), "a");
+        assert_eq!(full(&["a\\b"], Some(3)), "a\\\\");
+        assert_eq!(full(&["a\\,b"], Some(4)), "a\\\\");
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        assert_eq!(full(&["/été/ü"], Some(3)), "/ét");
+        assert_eq!(full(&["/été/ü"], Some(6)), "/été/ü");
+    }
+
+    #[test]
+    fn keeps_the_first_component() {
+        let files = ["/home/a/b", "/home/c", "src/main.rs", "/var//x", "./y"].map(String::from);
+        assert_eq!(
+            CurrentFiles::TopLevel.render(&files, None),
+            (Some("/home,src,/var,.".to_string()), None)
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use crate::log::verbose;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use influxdb::{InfluxDbWriteable, WriteQuery};
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading the input failed, e.g. a line that isn't UTF-8
+    Io(io::Error),
+    /// A line that isn't JSON, e.g. a log line from a wrapper script
+    Json(serde_json::Error),
+    /// JSON that isn't a restic message, or a message missing fields
+    Schema(String),
+    /// The points couldn't be written to the output
+    Backend(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Json(e) => write!(f, "JSON error: {}", e),
+            Error::Schema(e) => write!(f, "schema error: {}", e),
+            Error::Backend(e) => write!(f, "backend error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+/// What to do when an error of a category happens
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Count it and carry on
+    Skip,
+    /// Count it, print it and carry on
+    Warn,
+    /// Stop processing
+    Abort,
+}
+
+#[derive(InfluxDbWriteable, Debug)]
+struct IngestErrors {
+    time: DateTime<Utc>,
+    io_errors: u64,
+    json_errors: u64,
+    schema_errors: u64,
+    backend_errors: u64,
+}
+
+/// Counts errors by category and applies the policy chosen for each. Shared
+/// between the reader and the writer task.
+pub struct Errors {
+    policies: [Policy; 4],
+    counts: [AtomicU64; 4],
+}
+
+impl Errors {
+    pub fn new(io: Policy, json: Policy, schema: Policy, backend: Policy) -> Self {
+        Errors {
+            policies: [io, json, schema, backend],
+            counts: Default::default(),
+        }
+    }
+
+    fn index(error: &Error) -> usize {
+        match error {
+            Error::Io(_) => 0,
+            Error::Json(_) => 1,
+            Error::Schema(_) => 2,
+            Error::Backend(_) => 3,
+        }
+    }
+
+    /// Whether backend errors are printed, including the retries
+    pub fn warn_backend(&self) -> bool {
+        self.policies[3] != Policy::Skip
+    }
+
+    /// Counts the error, and returns it when its category aborts
+    pub fn handle(&self, error: Error) -> Result<(), Error> {
+        let index = Self::index(&error);
+        self.counts[index].fetch_add(1, Ordering::Relaxed);
+        match self.policies[index] {
+            Policy::Skip => {
+                verbose!("Skipped, {}", error);
+                Ok(())
+            }
+            Policy::Warn => {
+                eprintln!("{}", error);
+                Ok(())
+            }
+            Policy::Abort => Err(error),
+        }
+    }
+
+    /// The `ingest_errors` point, with the number of errors of each category
+    pub fn query(&self) -> WriteQuery {
+        let count = |index: usize| self.counts[index].load(Ordering::Relaxed);
+        IngestErrors {
+            time: Utc::now(),
+            io_errors: count(0),
+            json_errors: count(1),
+            schema_errors: count(2),
+            backend_errors: count(3),
+        }
+        .into_query("ingest_errors")
+    }
+}
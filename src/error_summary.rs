@@ -0,0 +1,80 @@
+use chrono::{DateTime, Utc};
+use influxdb::{InfluxDbWriteable, WriteQuery};
+use std::collections::BTreeMap;
+
+// Number of errors of a phase under a path prefix
+#[derive(InfluxDbWriteable, Debug)]
+struct ErrorSummary {
+    time: DateTime<Utc>,
+    // left out when empty, InfluxDB rejects empty tags
+    #[influxdb(tag)]
+    during: Option<String>,
+    #[influxdb(tag)]
+    prefix: Option<String>,
+    count: u64,
+}
+
+/// Counts restic's errors by `during` (scan, archival, ...) and by the first
+/// components of the item's path, so that thousands of errors on a directory
+/// end up as a few points. Individual error points are capped.
+pub struct ErrorAggregator {
+    depth: usize,
+    max_points: u64,
+    points: u64,
+    counts: BTreeMap<(String, String), u64>,
+}
+
+impl ErrorAggregator {
+    pub fn new(depth: usize, max_points: u64) -> Self {
+        ErrorAggregator {
+            depth,
+            max_points,
+            points: 0,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Counts an error, returns whether its own point is written
+    pub fn add(&mut self, during: &str, item: &str) -> bool {
+        *self
+            .counts
+            .entry((during.to_string(), prefix(item, self.depth)))
+            .or_default() += 1;
+        if self.points == self.max_points {
+            eprintln!(
+                "More than {} errors, the next ones are only counted in error_summary",
+                self.max_points
+            );
+        }
+        self.points += 1;
+        self.points <= self.max_points
+    }
+
+    /// The error_summary points, one per phase and prefix
+    pub fn queries(self, time: DateTime<Utc>) -> Vec<WriteQuery> {
+        self.counts
+            .into_iter()
+            .map(|((during, prefix), count)| {
+                ErrorSummary {
+                    time,
+                    during: Some(during).filter(|during| !during.is_empty()),
+                    prefix: Some(prefix).filter(|prefix| !prefix.is_empty()),
+                    count,
+                }
+                .into_query("error_summary")
+            })
+            .collect()
+    }
+}
+
+// The first `depth` components of a path, e.g. /home/alice for
+// /home/alice/.cache/x with a depth of 2
+fn prefix(path: &str, depth: usize) -> String {
+    let root = if path.starts_with('/') { "/" } else { "" };
+    let components: Vec<&str> = path
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .take(depth)
+        .collect();
+    format!("{}{}", root, components.join("/"))
+}
@@ -0,0 +1,75 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+// Number of -v flags, read by the reader and the writer task
+static LEVEL: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_level(level: u8) {
+    LEVEL.store(level, Ordering::Relaxed);
+}
+
+pub fn enabled(level: u8) -> bool {
+    LEVEL.load(Ordering::Relaxed) >= level
+}
+
+/// Prints to stderr with `-v`: decisions, timings and the final summary
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled(1) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Prints to stderr with `-vv`: the input lines and the points in full
+macro_rules! dump {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled(2) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use dump;
+pub(crate) use verbose;
+
+/// What happened to the input and the points, summed up on exit
+pub struct Counters {
+    pub lines_read: AtomicU64,
+    pub lines_parsed: AtomicU64,
+    pub points_queued: AtomicU64,
+    pub points_throttled: AtomicU64,
+    pub points_dropped: AtomicU64,
+    pub points_written: AtomicU64,
+    pub points_spooled: AtomicU64,
+}
+
+pub static COUNTERS: Counters = Counters {
+    lines_read: AtomicU64::new(0),
+    lines_parsed: AtomicU64::new(0),
+    points_queued: AtomicU64::new(0),
+    points_throttled: AtomicU64::new(0),
+    points_dropped: AtomicU64::new(0),
+    points_written: AtomicU64::new(0),
+    points_spooled: AtomicU64::new(0),
+};
+
+/// Adds to a counter, returns the new value
+pub fn count(counter: &AtomicU64, n: u64) -> u64 {
+    counter.fetch_add(n, Ordering::Relaxed) + n
+}
+
+impl Counters {
+    pub fn summary(&self) -> String {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        format!(
+            "Read {} lines, parsed {} messages, throttled {} and queued {} points, wrote {}, spooled {}, dropped {}",
+            get(&self.lines_read),
+            get(&self.lines_parsed),
+            get(&self.points_throttled),
+            get(&self.points_queued),
+            get(&self.points_written),
+            get(&self.points_spooled),
+            get(&self.points_dropped),
+        )
+    }
+}
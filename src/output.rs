@@ -0,0 +1,239 @@
+use crate::error::{Error, Errors};
+use crate::log::{self, dump, COUNTERS};
+use crate::point::Point;
+use crate::prometheus::Prometheus;
+use crate::schema::Schema;
+use crate::writer::Writer;
+use influxdb::{Query, WriteQuery};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Where the points end up
+pub enum Sink {
+    InfluxDb(Writer),
+    Prometheus(Prometheus),
+    LineProtocol(LineProtocol),
+}
+
+/// Writes points as InfluxDB line protocol, one per line, for Telegraf or
+/// `influx write`
+pub struct LineProtocol {
+    out: Box<dyn Write + Send>,
+    // unsigned integers as `1u` instead of `1i`, as understood by InfluxDB 2
+    use_v2: bool,
+}
+
+/// Stdout for `-`, the file opened for appending otherwise
+pub fn open(path: &Path) -> io::Result<Box<dyn Write + Send>> {
+    Ok(if path == Path::new("-") {
+        Box::new(io::stdout())
+    } else {
+        Box::new(OpenOptions::new().create(true).append(true).open(path)?)
+    })
+}
+
+impl LineProtocol {
+    pub fn open(path: &Path, use_v2: bool) -> io::Result<Self> {
+        Ok(LineProtocol {
+            out: open(path)?,
+            use_v2,
+        })
+    }
+
+    fn write(
+        &mut self,
+        query: &WriteQuery,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let line = query.build_with_opts(self.use_v2)?.get();
+        writeln!(self.out, "{}", line)?;
+        Ok(())
+    }
+}
+
+/// Receives every point, adds the common tags and hands it to the sink
+pub struct Output {
+    sink: Sink,
+    errors: Arc<Errors>,
+    dry_run: bool,
+    tags: Vec<(String, String)>,
+    schema: Schema,
+}
+
+impl Output {
+    pub fn new(sink: Sink, errors: Arc<Errors>) -> Self {
+        Output {
+            sink,
+            errors,
+            dry_run: false,
+            tags: Vec::new(),
+            schema: Schema::default(),
+        }
+    }
+
+    /// Print the points instead of sending them
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Tags added to every point
+    pub fn with_tags(mut self, tags: Vec<(String, String)>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Measurement and field names, Prometheus metrics keep restic's names
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    /// Takes what previous runs couldn't send, see `replay_batch`
+    pub fn load_spool(&mut self) {
+        if let (Sink::InfluxDb(writer), false) = (&mut self.sink, self.dry_run) {
+            writer.load_spool();
+        }
+    }
+
+    pub fn replaying(&self) -> bool {
+        matches!(&self.sink, Sink::InfluxDb(writer) if writer.replaying())
+    }
+
+    /// Sends the next batch of points that previous runs couldn't
+    pub async fn replay_batch(&mut self) -> Result<(), Error> {
+        match &mut self.sink {
+            Sink::InfluxDb(writer) => writer.replay_batch().await,
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn push(&mut self, mut query: WriteQuery) -> Result<(), Error> {
+        for (key, value) in &self.tags {
+            query = query.add_tag(key, value.as_str());
+        }
+        if !self.schema.is_empty() && !matches!(self.sink, Sink::Prometheus(_)) {
+            query = match Point::from_query(&query) {
+                Ok(point) => self.schema.apply(point).into_query(),
+                Err(e) => return self.errors.handle(Error::Backend(e)),
+            };
+        }
+        dump!(
+            "point: {}",
+            query.build().map(|q| q.get()).unwrap_or_default()
+        );
+        if self.dry_run {
+            println!("-> {:?}", query);
+            log::count(&COUNTERS.points_written, 1);
+            return Ok(());
+        }
+        match &mut self.sink {
+            Sink::InfluxDb(writer) => writer.push(query).await,
+            Sink::Prometheus(prometheus) => match Point::from_query(&query) {
+                Ok(point) => {
+                    if prometheus.push(point) {
+                        log::count(&COUNTERS.points_written, 1);
+                    }
+                    Ok(())
+                }
+                Err(e) => self.errors.handle(Error::Backend(e)),
+            },
+            Sink::LineProtocol(out) => match out.write(&query) {
+                Ok(()) => {
+                    log::count(&COUNTERS.points_written, 1);
+                    Ok(())
+                }
+                Err(e) => self.errors.handle(Error::Backend(e.to_string())),
+            },
+        }
+    }
+
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        let result = match &mut self.sink {
+            Sink::InfluxDb(writer) => return writer.flush().await,
+            Sink::Prometheus(prometheus) => prometheus.flush().await,
+            Sink::LineProtocol(out) => out.out.flush().map_err(|e| e.into()),
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => self.errors.handle(Error::Backend(e.to_string())),
+        }
+    }
+
+    /// Writes the error counts and sends everything that's left
+    pub async fn finish(&mut self) -> Result<(), Error> {
+        while self.replaying() {
+            self.replay_batch().await?;
+        }
+        let query = self.errors.query();
+        self.push(query).await?;
+        self.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use influxdb::Timestamp;
+    use std::fs;
+
+    // Lines written by a LineProtocol sink to a fresh file
+    fn written(name: &str, use_v2: bool, queries: &[WriteQuery]) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "restic-to-influxdb-{}-{}.txt",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        let mut out = LineProtocol::open(&path, use_v2).unwrap();
+        for query in queries {
+            out.write(query).unwrap();
+        }
+        out.out.flush().unwrap();
+        drop(out);
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        text
+    }
+
+    fn summary() -> WriteQuery {
+        WriteQuery::new(
+            Timestamp::Nanoseconds(1_700_000_000_123_456_789),
+            "summary_message",
+        )
+        .add_tag("host", "vm")
+        .add_field("files_new", 3u64)
+        .add_field("total_duration", 1.5)
+        .add_field("dry_run", false)
+        .add_field("snapshot_id", "abc")
+    }
+
+    #[test]
+    fn writes_unsigned_integers_as_signed_for_v1() {
+        assert_eq!(
+            written("v1", false, &[summary()]),
+            "summary_message,host=vm files_new=3i,total_duration=1.5,dry_run=false,snapshot_id=\"abc\" 1700000000123456789\n"
+        );
+    }
+
+    #[test]
+    fn writes_unsigned_integers_for_v2() {
+        assert_eq!(
+            written("v2", true, &[summary(), summary()]),
+            "summary_message,host=vm files_new=3u,total_duration=1.5,dry_run=false,snapshot_id=\"abc\" 1700000000123456789\n"
+                .repeat(2)
+        );
+    }
+
+    #[test]
+    fn escapes_names_and_values() {
+        let query = WriteQuery::new(Timestamp::Nanoseconds(1), "a b,c")
+            .add_tag("tag key", "a,b=c")
+            .add_field("field key", "say \"hi\" \\o/");
+        assert_eq!(
+            written("escape", true, &[query]),
+            "a\\ b\\,c,tag\\ key=a\\,b\\=c field\\ key=\"say \\\"hi\\\" \\\\o/\" 1\n"
+        );
+    }
+}
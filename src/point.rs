@@ -0,0 +1,215 @@
+use influxdb::{Query, Timestamp, Type, WriteQuery};
+
+/// A point whose measurement, tags and fields can be read back, for outputs
+/// that don't speak line protocol. `WriteQuery` keeps those private, so the
+/// point is parsed from the line protocol it builds.
+#[derive(Debug, Clone)]
+pub struct Point {
+    pub measurement: String,
+    pub tags: Vec<(String, String)>,
+    pub fields: Vec<(String, Type)>,
+    /// Nanoseconds since the epoch
+    pub timestamp: u128,
+}
+
+impl Point {
+    pub fn from_query(query: &WriteQuery) -> Result<Self, String> {
+        let line = query
+            .build_with_opts(true)
+            .map_err(|e| e.to_string())?
+            .get();
+        Self::parse(&line)
+    }
+
+    pub fn into_query(self) -> WriteQuery {
+        let mut query = WriteQuery::new(Timestamp::Nanoseconds(self.timestamp), self.measurement);
+        for (key, value) in self.tags {
+            query = query.add_tag(key, value);
+        }
+        for (key, value) in self.fields {
+            query = query.add_field(key, value);
+        }
+        query
+    }
+
+    /// Parses a line of line protocol, as built with unsigned integer support
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut chars = line.chars().peekable();
+        let mut measurement = None;
+        let mut tags = Vec::new();
+        let mut key = None;
+        let mut current = String::new();
+        // measurement and tags, up to the first unescaped space
+        loop {
+            let c = chars.next();
+            match c {
+                Some('\\') => current.extend(chars.next()),
+                Some('=') if measurement.is_some() && key.is_none() => {
+                    key = Some(std::mem::take(&mut current))
+                }
+                Some(',' | ' ') | None => {
+                    let part = std::mem::take(&mut current);
+                    match (&measurement, key.take()) {
+                        (None, _) => measurement = Some(part),
+                        (Some(_), Some(key)) => tags.push((key, part)),
+                        (Some(_), None) => return Err(format!("invalid tag `{}`", part)),
+                    }
+                    if c != Some(',') {
+                        break;
+                    }
+                }
+                Some(c) => current.push(c),
+            }
+        }
+        let measurement = measurement.unwrap_or_default();
+
+        let mut fields = Vec::new();
+        loop {
+            let mut key = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => key.extend(chars.next()),
+                    '=' => break,
+                    _ => key.push(c),
+                }
+            }
+            let mut value = String::new();
+            let mut quoted = false;
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                quoted = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next()),
+                        '"' => break,
+                        _ => value.push(c),
+                    }
+                }
+            }
+            let mut end = None;
+            for c in chars.by_ref() {
+                if c == ',' || c == ' ' {
+                    end = Some(c);
+                    break;
+                }
+                value.push(c);
+            }
+            let value = if quoted {
+                Type::Text(value)
+            } else {
+                parse_field_value(&value).ok_or_else(|| format!("invalid field `{}`", key))?
+            };
+            fields.push((key, value));
+            if end != Some(',') {
+                break;
+            }
+        }
+
+        let timestamp = chars.collect::<String>();
+        let timestamp = timestamp
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid timestamp `{}`", timestamp))?;
+
+        Ok(Point {
+            measurement,
+            tags,
+            fields,
+            timestamp,
+        })
+    }
+
+    /// Numeric value of a field, for outputs that only deal with numbers
+    pub fn number(value: &Type) -> Option<f64> {
+        match value {
+            Type::Boolean(b) => Some(if *b { 1.0 } else { 0.0 }),
+            Type::Float(f) => Some(*f),
+            Type::SignedInteger(i) => Some(*i as f64),
+            Type::UnsignedInteger(u) => Some(*u as f64),
+            Type::Text(_) => None,
+        }
+    }
+}
+
+fn parse_field_value(value: &str) -> Option<Type> {
+    match value {
+        "t" | "T" | "true" | "True" | "TRUE" => return Some(Type::Boolean(true)),
+        "f" | "F" | "false" | "False" | "FALSE" => return Some(Type::Boolean(false)),
+        _ => {}
+    }
+    if let Some(i) = value.strip_suffix('i') {
+        return i.parse().ok().map(Type::SignedInteger);
+    }
+    if let Some(u) = value.strip_suffix('u') {
+        return u.parse().ok().map(Type::UnsignedInteger);
+    }
+    value.parse().ok().map(Type::Float)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(query: &WriteQuery) -> String {
+        query.build_with_opts(true).unwrap().get()
+    }
+
+    #[test]
+    fn round_trips_escaped_names_and_values() {
+        let query = WriteQuery::new(Timestamp::Nanoseconds(1_700_000_000_000_000_000), "a b,c")
+            .add_tag("tag key,=", "va lue,=\\")
+            .add_tag("path", "C:\\restic")
+            .add_field("field key,=", "say \"hi\", \\o/")
+            .add_field("count", 3u64);
+        let point = Point::from_query(&query).unwrap();
+        assert_eq!(point.measurement, "a b,c");
+        assert_eq!(
+            point.tags,
+            vec![
+                ("tag key,=".to_string(), "va lue,=\\".to_string()),
+                ("path".to_string(), "C:\\restic".to_string()),
+            ]
+        );
+        assert_eq!(point.fields[0].0, "field key,=");
+        assert!(matches!(&point.fields[0].1, Type::Text(text) if text == "say \"hi\", \\o/"));
+        assert_eq!(point.timestamp, 1_700_000_000_000_000_000);
+        assert_eq!(line(&point.into_query()), line(&query));
+    }
+
+    #[test]
+    fn parses_field_types() {
+        let point = Point::parse("m i=-1i,u=2u,f=1.5,e=1e3,t=true,b=F,s=\"1i\" 42").unwrap();
+        let fields: Vec<(&str, &Type)> =
+            point.fields.iter().map(|(k, v)| (k.as_str(), v)).collect();
+        assert!(matches!(fields[0], ("i", Type::SignedInteger(-1))));
+        assert!(matches!(fields[1], ("u", Type::UnsignedInteger(2))));
+        assert!(matches!(fields[2], ("f", Type::Float(f)) if *f == 1.5));
+        assert!(matches!(fields[3], ("e", Type::Float(f)) if *f == 1000.0));
+        assert!(matches!(fields[4], ("t", Type::Boolean(true))));
+        assert!(matches!(fields[5], ("b", Type::Boolean(false))));
+        assert!(matches!(fields[6], ("s", Type::Text(s)) if s == "1i"));
+        assert_eq!(point.timestamp, 42);
+        assert!(point.tags.is_empty());
+    }
+
+    #[test]
+    fn round_trips_every_field_type() {
+        let query = WriteQuery::new(Timestamp::Nanoseconds(1), "m")
+            .add_tag("host", "vm")
+            .add_field("i", -7i64)
+            .add_field("u", u64::MAX)
+            .add_field("f", 0.25)
+            .add_field("b", true)
+            .add_field("s", "");
+        let point = Point::from_query(&query).unwrap();
+        assert_eq!(point.fields.len(), 5);
+        assert_eq!(line(&point.into_query()), line(&query));
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        assert!(Point::parse("m,tag v=1 1").is_err());
+        assert!(Point::parse("m v=nope 1").is_err());
+        assert!(Point::parse("m v=1i").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::log::verbose;
+use crate::point::Point;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Where the Prometheus metrics go
+pub enum PrometheusTarget {
+    /// A file in the node_exporter textfile collector directory
+    Textfile(PathBuf),
+    /// A Pushgateway base URL, e.g. http://localhost:9091
+    Pushgateway(String),
+}
+
+/// Exposes the backup summary as gauges. Metrics are kept in memory and
+/// replace the previous ones when flushed, so each job needs its own textfile
+/// or its own `--job` when using a Pushgateway.
+pub struct Prometheus {
+    target: PrometheusTarget,
+    job: String,
+    http: reqwest::Client,
+    // metric name -> labels -> value
+    metrics: BTreeMap<String, BTreeMap<String, f64>>,
+}
+
+impl Prometheus {
+    pub fn new(target: PrometheusTarget, job: String, http: reqwest::Client) -> Self {
+        Prometheus {
+            target,
+            job,
+            http,
+            metrics: BTreeMap::new(),
+        }
+    }
+
+    /// Updates the metrics from a summary point, other points are ignored
+    pub fn push(&mut self, point: Point) -> bool {
+        if point.measurement != "summary_message" {
+            return false;
+        }
+        // the Pushgateway sets the job label from the grouping key, and a
+        // run_id label would start new series on every run
+        let tags = point.tags.iter().filter(|(key, _)| {
+            key != "run_id"
+                && !(matches!(self.target, PrometheusTarget::Pushgateway(_)) && key == "job")
+        });
+        let labels = tags
+            .map(|(key, value)| format!("{}=\"{}\"", sanitize(key), escape(value)))
+            .collect::<Vec<String>>()
+            .join(",");
+        for (field, value) in &point.fields {
+            if let Some(value) = Point::number(value) {
+                self.set(
+                    format!("restic_summary_{}", sanitize(field)),
+                    &labels,
+                    value,
+                );
+            }
+        }
+        self.set(
+            "restic_last_success_timestamp_seconds".to_string(),
+            &labels,
+            point.timestamp as f64 / 1e9,
+        );
+        true
+    }
+
+    fn set(&mut self, name: String, labels: &str, value: f64) {
+        self.metrics
+            .entry(name)
+            .or_default()
+            .insert(labels.to_string(), value);
+    }
+
+    // Text exposition format
+    fn render(&self) -> String {
+        let mut text = String::new();
+        for (name, series) in &self.metrics {
+            text.push_str(&format!("# TYPE {} gauge\n", name));
+            for (labels, value) in series {
+                text.push_str(&format!("{}{{{}}} {}\n", name, labels, value));
+            }
+        }
+        text
+    }
+
+    pub async fn flush(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.metrics.is_empty() {
+            return Ok(());
+        }
+        let text = self.render();
+        let start = std::time::Instant::now();
+        match &self.target {
+            PrometheusTarget::Textfile(path) => {
+                // node_exporter must never see a partially written file
+                let tmp = path.with_extension("prom.tmp");
+                fs::write(&tmp, text)?;
+                fs::rename(&tmp, path)?;
+            }
+            PrometheusTarget::Pushgateway(url) => {
+                let mut url = reqwest::Url::parse(url)?;
+                url.path_segments_mut()
+                    .map_err(|_| "invalid Pushgateway URL")?
+                    .pop_if_empty()
+                    .extend(["metrics", "job", &self.job]);
+                let res = self.http.put(url).body(text).send().await?;
+                if !res.status().is_success() {
+                    let status = res.status();
+                    let body = res.text().await.unwrap_or_default();
+                    return Err(format!("Pushgateway push failed ({}): {}", status, body).into());
+                }
+            }
+        }
+        verbose!(
+            "Wrote {} metrics in {:?}",
+            self.metrics.len(),
+            start.elapsed()
+        );
+        Ok(())
+    }
+}
+
+// Metric and label names are limited to [a-zA-Z0-9_]
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,129 @@
+use crate::error::Error;
+use crate::log::{self, verbose, COUNTERS};
+use crate::output::Output;
+use clap::ValueEnum;
+use influxdb::WriteQuery;
+use std::cell::{Cell, RefCell};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
+
+/// What happens to a status point when the queue to the output is full
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// Drop the status point, restic is never slowed down
+    Drop,
+    /// Wait for the output to catch up, restic blocks on a full pipe
+    Block,
+}
+
+/// Bounded queue between the reader, that parses restic's output, and the
+/// task that writes the points, so that a slow database doesn't stall the
+/// reader and in turn restic. Pushing blocks the calling thread, the reader
+/// runs outside of the async runtime (see `tokio::task::block_in_place`).
+pub struct Queue {
+    tx: mpsc::Sender<WriteQuery>,
+    overflow: Overflow,
+    dropped: Cell<u64>,
+    // run_id of the replayed run being read, set per run
+    run_id: RefCell<Option<String>>,
+}
+
+impl Queue {
+    /// Starts the writer task, that sends the queued points to `output` and
+    /// flushes it every `flush_interval` when the input is idle. The spool of
+    /// previous runs is sent while no point is waiting.
+    pub fn spawn(
+        mut output: Output,
+        size: usize,
+        overflow: Overflow,
+        flush_interval: Duration,
+    ) -> (Queue, JoinHandle<Result<(), Error>>) {
+        let (tx, mut rx) = mpsc::channel(size.max(1));
+        // stops on the first error that aborts, the reader then fails to push
+        let writer = tokio::spawn(async move {
+            loop {
+                let idle = if output.replaying() {
+                    Duration::ZERO
+                } else {
+                    flush_interval
+                };
+                match tokio::time::timeout(idle, rx.recv()).await {
+                    Ok(Some(query)) => output.push(query).await?,
+                    Ok(None) => break,
+                    Err(_) if output.replaying() => output.replay_batch().await?,
+                    Err(_) => output.flush().await?,
+                }
+            }
+            output.finish().await
+        });
+        let queue = Queue {
+            tx,
+            overflow,
+            dropped: Cell::new(0),
+            run_id: RefCell::new(None),
+        };
+        (queue, writer)
+    }
+
+    /// Tags the points pushed from now on with `run_id`, for the runs of a
+    /// replayed log, the common tags hold no run_id then
+    pub fn set_run_id(&self, run_id: Option<String>) {
+        self.run_id.replace(run_id);
+    }
+
+    fn tag(&self, query: WriteQuery) -> WriteQuery {
+        match &*self.run_id.borrow() {
+            Some(run_id) => query.add_tag("run_id", run_id.as_str()),
+            None => query,
+        }
+    }
+
+    /// Queues a point, waiting for room if needed
+    pub fn push(&self, query: WriteQuery) -> Result<(), Error> {
+        let query = self.tag(query);
+        self.tx.blocking_send(query).map_err(|_| Self::stopped())?;
+        log::count(&COUNTERS.points_queued, 1);
+        Ok(())
+    }
+
+    /// Queues a status point, that can be dropped when the queue is full
+    pub fn push_status(&self, query: WriteQuery) -> Result<(), Error> {
+        if self.overflow == Overflow::Block {
+            return self.push(query);
+        }
+        match self.tx.try_send(self.tag(query)) {
+            Ok(()) => {
+                log::count(&COUNTERS.points_queued, 1);
+                Ok(())
+            }
+            Err(TrySendError::Full(_)) => {
+                self.dropped.set(self.dropped.get() + 1);
+                log::count(&COUNTERS.points_dropped, 1);
+                verbose!("Status point dropped, the queue is full");
+                Ok(())
+            }
+            Err(TrySendError::Closed(_)) => Err(Self::stopped()),
+        }
+    }
+
+    // The writer task is gone, the error it stopped on is returned by `close`
+    fn stopped() -> Error {
+        Error::Backend("the output stopped".to_string())
+    }
+
+    /// Waits for the writer task to send everything that was queued
+    pub async fn close(self, writer: JoinHandle<Result<(), Error>>) -> Result<(), Error> {
+        if self.dropped.get() > 0 {
+            eprintln!(
+                "Dropped {} status points, the output couldn't keep up",
+                self.dropped.get()
+            );
+        }
+        drop(self.tx);
+        match writer.await {
+            Ok(result) => result,
+            Err(e) => Err(Error::Backend(e.to_string())),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde_json::Value;
+
+/// Where the timestamps of the points come from
+pub enum Clock {
+    /// Messages are processed as restic emits them
+    Wall,
+    /// Messages are read from a saved log, times are derived from the start
+    /// of the backup and the elapsed time reported by restic
+    Replay {
+        start: DateTime<Utc>,
+        latest: DateTime<Utc>,
+    },
+}
+
+impl Clock {
+    pub fn replay(start: DateTime<Utc>) -> Self {
+        Clock::Replay {
+            start,
+            latest: start,
+        }
+    }
+
+    /// Time of a message that carries no time information
+    pub fn now(&self) -> DateTime<Utc> {
+        match self {
+            Clock::Wall => Utc::now(),
+            Clock::Replay { latest, .. } => *latest,
+        }
+    }
+
+    /// Time of a message emitted `seconds` after the start of the backup
+    pub fn at_elapsed(&mut self, seconds: f64) -> DateTime<Utc> {
+        match self {
+            Clock::Wall => Utc::now(),
+            Clock::Replay { start, latest } => {
+                let time = *start + TimeDelta::nanoseconds((seconds * 1e9) as i64);
+                *latest = time.max(*latest);
+                time
+            }
+        }
+    }
+
+    /// Time of a message that carries its own timestamp
+    pub fn at(&mut self, time: DateTime<Utc>) -> DateTime<Utc> {
+        if let Clock::Replay { latest, .. } = self {
+            *latest = time.max(*latest);
+        }
+        time
+    }
+}
+
+/// Splits a saved log into runs, each ending with its summary message, so
+/// that a log with several backups gets one start time per backup.
+pub fn split_runs<I: Iterator<Item = String>>(lines: I) -> Vec<Vec<String>> {
+    let mut runs = vec![Vec::new()];
+    for line in lines {
+        let is_summary = line.contains("\"summary\"")
+            && serde_json::from_str::<Value>(&line)
+                .is_ok_and(|message| message["message_type"] == "summary");
+        runs.last_mut().unwrap().push(line);
+        if is_summary {
+            runs.push(Vec::new());
+        }
+    }
+    runs.retain(|run| !run.is_empty());
+    runs
+}
+
+/// Whether a run holds restic messages, and not only lines logged around
+/// them, e.g. by a wrapper script after the backup
+pub fn has_messages(run: &[String]) -> bool {
+    run.iter().any(|line| {
+        serde_json::from_str::<Value>(line).is_ok_and(|message| message["message_type"].is_string())
+    })
+}
+
+/// Start of a run, from `backup_start` in its summary. restic added it with
+/// `backup_end`, older versions give no way to place a run in time.
+pub fn infer_start(run: &[String]) -> Option<DateTime<Utc>> {
+    let summary: Value = serde_json::from_str(run.last()?).ok()?;
+    if summary["message_type"] != "summary" {
+        return None;
+    }
+    let start = DateTime::parse_from_rfc3339(summary["backup_start"].as_str()?).ok()?;
+    Some(start.with_timezone(&Utc))
+}
+
+/// run_id of a replayed run: the snapshot it created, or its start time when
+/// the summary has no snapshot_id (`--dry-run`)
+pub fn run_id(run: &[String], start: DateTime<Utc>) -> String {
+    let summary: Option<Value> = run.last().and_then(|line| serde_json::from_str(line).ok());
+    match summary
+        .as_ref()
+        .and_then(|summary| summary["snapshot_id"].as_str())
+    {
+        Some(snapshot_id) => snapshot_id.to_string(),
+        None => start.to_rfc3339(),
+    }
+}
@@ -0,0 +1,92 @@
+use crate::throughput::Progress;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use influxdb::InfluxDbWriteable;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// restic command whose output is being read
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResticCommand {
+    Backup,
+    Restore,
+}
+
+// Fields only present in the output of `restic restore --json`
+const RESTORE_FIELDS: [&str; 5] = [
+    "files_restored",
+    "bytes_restored",
+    "files_skipped",
+    "bytes_skipped",
+    "files_deleted",
+];
+
+// Fields only present in the output of `restic backup --json`. Status counters
+// are omitted while zero too, the first statuses of a backup have none.
+const BACKUP_FIELDS: [&str; 4] = ["files_done", "bytes_done", "current_files", "files_new"];
+
+impl ResticCommand {
+    /// The restic subcommand in a command line, e.g. `restic -r repo restore latest`
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        args.iter().skip(1).find_map(|arg| match arg.as_str() {
+            "backup" => Some(ResticCommand::Backup),
+            "restore" => Some(ResticCommand::Restore),
+            _ => None,
+        })
+    }
+
+    /// Recognizes the command by the fields of a message. Fields are omitted
+    /// while zero, an early status can come from either command.
+    pub fn detect(message: &Map<String, Value>) -> Option<Self> {
+        let has = |fields: &[&str]| fields.iter().any(|field| message.contains_key(*field));
+        if has(&RESTORE_FIELDS) {
+            Some(ResticCommand::Restore)
+        } else if has(&BACKUP_FIELDS) {
+            Some(ResticCommand::Backup)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(InfluxDbWriteable, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RestoreStatusMessage {
+    pub time: DateTime<Utc>,
+    message_type: String,
+    pub seconds_elapsed: u64,
+    pub percent_done: f64,
+    total_files: u64,
+    files_restored: u64,
+    files_skipped: u64,
+    files_deleted: u64,
+    total_bytes: u64,
+    bytes_restored: u64,
+    bytes_skipped: u64,
+}
+
+impl RestoreStatusMessage {
+    pub fn progress(&self) -> Progress {
+        Progress {
+            seconds_elapsed: self.seconds_elapsed,
+            bytes_done: self.bytes_restored,
+            files_done: self.files_restored,
+            total_bytes: self.total_bytes,
+        }
+    }
+}
+
+#[derive(InfluxDbWriteable, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RestoreSummaryMessage {
+    pub time: DateTime<Utc>,
+    message_type: String,
+    pub seconds_elapsed: u64,
+    total_files: u64,
+    files_restored: u64,
+    files_skipped: u64,
+    files_deleted: u64,
+    total_bytes: u64,
+    bytes_restored: u64,
+    bytes_skipped: u64,
+}
@@ -0,0 +1,140 @@
+use crate::error::{Error, Errors};
+use crate::queue::Queue;
+use crate::replay::Clock;
+use crate::restore::ResticCommand;
+use crate::tee::Tee;
+use crate::{process, Cli, Outcome};
+use chrono::{DateTime, Utc};
+use influxdb::InfluxDbWriteable;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Instant, SystemTime};
+
+// Number of restic stderr lines kept for the run_result point
+const STDERR_TAIL_LINES: usize = 10;
+
+// exit_code and stderr are only known when restic is run by us
+#[derive(InfluxDbWriteable, Debug)]
+struct RunResult {
+    time: DateTime<Utc>,
+    exit_code: Option<i64>,
+    duration: f64,
+    summary_seen: bool,
+    snapshot_id: Option<String>,
+    stderr: Option<String>,
+}
+
+/// Writes the `run_result` point closing a run, with the snapshot created by
+/// the backup if any.
+pub fn record(
+    queue: &Queue,
+    start: Instant,
+    outcome: Option<&Outcome>,
+    exit_code: Option<i64>,
+    stderr: Option<String>,
+) -> Result<(), Error> {
+    let result = RunResult {
+        time: SystemTime::now().into(),
+        exit_code,
+        duration: start.elapsed().as_secs_f64(),
+        summary_seen: outcome.is_some_and(|o| o.summary_seen),
+        snapshot_id: outcome.and_then(|o| o.snapshot_id.clone()),
+        stderr,
+    };
+    queue.push(result.into_query("run_result"))
+}
+
+/// Writes the `run_result` point of a run read from a saved log, placed at its
+/// end as given by the replay clock
+pub fn record_replayed(
+    queue: &Queue,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    outcome: &Outcome,
+) -> Result<(), Error> {
+    let result = RunResult {
+        time: end,
+        exit_code: None,
+        duration: (end - start).to_std().unwrap_or_default().as_secs_f64(),
+        summary_seen: outcome.summary_seen,
+        snapshot_id: outcome.snapshot_id.clone(),
+        stderr: None,
+    };
+    queue.push(result.into_query("run_result"))
+}
+
+/// Spawns restic, feeds its JSON output to `process`, and writes a
+/// `run_result` point once it exits. Returns restic's exit code.
+pub fn run(
+    cli: &Cli,
+    queue: &Queue,
+    errors: &Errors,
+    args: &[String],
+    tee: Option<Box<dyn Write + Send>>,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let start = Instant::now();
+
+    let mut command = Command::new(&args[0]);
+    // global flag, placed before the restic subcommand so that a `--` in the
+    // arguments doesn't turn it into a path
+    if !args.iter().any(|arg| arg == "--json") {
+        command.arg("--json");
+    }
+    command
+        .args(&args[1..])
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            let stderr = format!("could not run {}: {}", args[0], e);
+            record(queue, start, None, Some(-1), Some(stderr))?;
+            return Err(e.into());
+        }
+    };
+
+    // forward restic's stderr as it comes, and keep the end of it
+    let stderr = BufReader::new(child.stderr.take().unwrap());
+    let stderr_thread = thread::spawn(move || {
+        let mut tail: Vec<String> = Vec::new();
+        for line in stderr.lines().map_while(Result::ok) {
+            eprintln!("{}", line);
+            if tail.len() == STDERR_TAIL_LINES {
+                tail.remove(0);
+            }
+            tail.push(line);
+        }
+        tail.join("\n")
+    });
+
+    let stdout = BufReader::new(Tee::new(child.stdout.take().unwrap(), tee));
+    let command = cli
+        .restic_command
+        .or_else(|| ResticCommand::from_args(args));
+    let outcome = process(
+        cli,
+        queue,
+        errors,
+        stdout.lines(),
+        &mut Clock::Wall,
+        command,
+    );
+
+    let status = child.wait()?;
+    let stderr = stderr_thread.join().unwrap_or_default();
+    let exit_code = status.code().unwrap_or(-1);
+
+    record(
+        queue,
+        start,
+        outcome.as_ref().ok(),
+        Some(exit_code.into()),
+        Some(stderr),
+    )?;
+    outcome?;
+
+    Ok(exit_code)
+}
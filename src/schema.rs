@@ -0,0 +1,89 @@
+use crate::point::Point;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Renames, drops and promotions of one measurement's fields
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Mapping {
+    /// New measurement name
+    name: Option<String>,
+    #[serde(default)]
+    rename: BTreeMap<String, String>,
+    #[serde(default)]
+    drop: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Maps the points to another naming scheme, from the `[schema]` table of
+/// the config file. The top level renames, drops and tags apply to every
+/// measurement, the ones of `[schema.measurements.<name>]` to that one.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schema {
+    #[serde(default)]
+    rename: BTreeMap<String, String>,
+    #[serde(default)]
+    drop: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    measurements: BTreeMap<String, Mapping>,
+}
+
+impl Schema {
+    pub fn is_empty(&self) -> bool {
+        self.rename.is_empty()
+            && self.drop.is_empty()
+            && self.tags.is_empty()
+            && self.measurements.is_empty()
+    }
+
+    /// Drops fields, then promotes fields to tags, then renames fields and
+    /// tags, matching the names restic uses
+    pub fn apply(&self, mut point: Point) -> Point {
+        let mapping = self.measurements.get(&point.measurement);
+        let listed = |name: &str, all: &[String], one: Option<&Vec<String>>| {
+            all.iter()
+                .chain(one.into_iter().flatten())
+                .any(|n| n == name)
+        };
+        let rename = |name: String| {
+            let renamed = mapping
+                .and_then(|mapping| mapping.rename.get(&name))
+                .or_else(|| self.rename.get(&name));
+            renamed.cloned().unwrap_or(name)
+        };
+
+        let fields = std::mem::take(&mut point.fields);
+        for (name, value) in fields {
+            if listed(&name, &self.drop, mapping.map(|m| &m.drop)) {
+                continue;
+            }
+            if listed(&name, &self.tags, mapping.map(|m| &m.tags)) {
+                // InfluxDB rejects empty tags, an empty text field is left out
+                let value = value.to_string();
+                if !value.is_empty() {
+                    point.tags.push((name, value));
+                }
+            } else {
+                point.fields.push((name, value));
+            }
+        }
+        point.fields = point
+            .fields
+            .into_iter()
+            .map(|(name, value)| (rename(name), value))
+            .collect();
+        point.tags = point
+            .tags
+            .into_iter()
+            .map(|(name, value)| (rename(name), value))
+            .collect();
+        if let Some(name) = mapping.and_then(|mapping| mapping.name.clone()) {
+            point.measurement = name;
+        }
+        point
+    }
+}
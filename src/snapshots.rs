@@ -0,0 +1,119 @@
+use crate::compression_space_saving;
+use crate::queue::Queue;
+use chrono::{DateTime, Utc};
+use influxdb::InfluxDbWriteable;
+use serde::Deserialize;
+use std::io::Read;
+
+// Stored in snapshots since restic 0.17
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SnapshotSummary {
+    backup_start: Option<DateTime<Utc>>,
+    backup_end: Option<DateTime<Utc>>,
+    files_new: Option<u64>,
+    files_changed: Option<u64>,
+    files_unmodified: Option<u64>,
+    dirs_new: Option<u64>,
+    dirs_changed: Option<u64>,
+    dirs_unmodified: Option<u64>,
+    data_blobs: Option<u64>,
+    tree_blobs: Option<u64>,
+    data_added: Option<u64>,
+    data_added_packed: Option<u64>,
+    total_files_processed: Option<u64>,
+    total_bytes_processed: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Snapshot {
+    time: DateTime<Utc>,
+    id: String,
+    #[serde(default)]
+    hostname: String,
+    #[serde(default)]
+    paths: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    summary: Option<SnapshotSummary>,
+}
+
+// Same measurement and field names as SummaryMessage, so that imported
+// snapshots line up with the summaries written at backup time. Snapshots
+// taken before restic 0.17 only have the snapshot_id.
+#[derive(InfluxDbWriteable, Debug, Default)]
+struct SnapshotPoint {
+    time: DateTime<Utc>,
+    #[influxdb(tag)]
+    host: Option<String>,
+    #[influxdb(tag)]
+    paths: Option<String>,
+    #[influxdb(tag)]
+    tags: Option<String>,
+    message_type: String,
+    snapshot_id: String,
+    data_added: Option<u64>,
+    data_added_packed: Option<u64>,
+    compression_space_saving: Option<f64>,
+    data_blobs: Option<u64>,
+    dirs_changed: Option<u64>,
+    dirs_new: Option<u64>,
+    dirs_unmodified: Option<u64>,
+    files_changed: Option<u64>,
+    files_new: Option<u64>,
+    files_unmodified: Option<u64>,
+    total_bytes_processed: Option<u64>,
+    total_duration: Option<f64>,
+    total_files_processed: Option<u64>,
+    tree_blobs: Option<u64>,
+}
+
+fn non_empty(values: &[String]) -> Option<String> {
+    (!values.is_empty()).then(|| values.join(","))
+}
+
+impl From<Snapshot> for SnapshotPoint {
+    fn from(snapshot: Snapshot) -> Self {
+        let mut point = SnapshotPoint {
+            time: snapshot.time,
+            host: (!snapshot.hostname.is_empty()).then_some(snapshot.hostname),
+            paths: non_empty(&snapshot.paths),
+            tags: non_empty(&snapshot.tags),
+            message_type: "summary".to_string(),
+            snapshot_id: snapshot.id,
+            ..Default::default()
+        };
+        if let Some(summary) = snapshot.summary {
+            point.data_added = summary.data_added;
+            point.data_added_packed = summary.data_added_packed;
+            point.compression_space_saving = summary
+                .data_added
+                .and_then(|added| compression_space_saving(added, summary.data_added_packed));
+            point.data_blobs = summary.data_blobs;
+            point.dirs_changed = summary.dirs_changed;
+            point.dirs_new = summary.dirs_new;
+            point.dirs_unmodified = summary.dirs_unmodified;
+            point.files_changed = summary.files_changed;
+            point.files_new = summary.files_new;
+            point.files_unmodified = summary.files_unmodified;
+            point.total_bytes_processed = summary.total_bytes_processed;
+            point.total_files_processed = summary.total_files_processed;
+            point.tree_blobs = summary.tree_blobs;
+            if let (Some(start), Some(end)) = (summary.backup_start, summary.backup_end) {
+                point.total_duration = (end - start).to_std().ok().map(|d| d.as_secs_f64());
+            }
+        }
+        point
+    }
+}
+
+/// Writes a summary_message point for each snapshot in the JSON array printed
+/// by `restic snapshots --json`.
+pub fn import<R: Read>(input: R, queue: &Queue) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshots: Vec<Snapshot> = serde_json::from_reader(input)?;
+    for snapshot in snapshots {
+        let point = SnapshotPoint::from(snapshot);
+        queue.push(point.into_query("summary_message"))?;
+    }
+    Ok(())
+}
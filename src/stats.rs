@@ -0,0 +1,59 @@
+use crate::queue::Queue;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use influxdb::InfluxDbWriteable;
+use serde::Deserialize;
+use std::io::Read;
+
+/// Counting mode passed to `restic stats --mode`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsMode {
+    RestoreSize,
+    RawData,
+    FilesByContents,
+    BlobsPerFile,
+}
+
+impl StatsMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            StatsMode::RestoreSize => "restore-size",
+            StatsMode::RawData => "raw-data",
+            StatsMode::FilesByContents => "files-by-contents",
+            StatsMode::BlobsPerFile => "blobs-per-file",
+        }
+    }
+}
+
+// The compression fields are only printed in raw-data mode, for repositories
+// using format version 2.
+#[derive(InfluxDbWriteable, Debug, Default, Deserialize)]
+#[serde(default)]
+struct RepoStats {
+    time: DateTime<Utc>,
+    #[influxdb(tag)]
+    mode: String,
+    total_size: u64,
+    total_uncompressed_size: Option<u64>,
+    compression_ratio: Option<f64>,
+    compression_progress: Option<f64>,
+    compression_space_saving: Option<f64>,
+    total_file_count: Option<u64>,
+    total_blob_count: Option<u64>,
+    snapshots_count: u64,
+}
+
+/// Writes a repo_stats point for each JSON object printed by `restic stats --json`
+pub fn import<R: Read>(
+    input: R,
+    mode: StatsMode,
+    queue: &Queue,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for stats in serde_json::Deserializer::from_reader(input).into_iter::<RepoStats>() {
+        let mut stats = stats?;
+        stats.time = Utc::now();
+        stats.mode = mode.as_str().to_string();
+        queue.push(stats.into_query("repo_stats"))?;
+    }
+    Ok(())
+}
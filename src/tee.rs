@@ -0,0 +1,29 @@
+use std::io::{self, Read, Write};
+
+/// Copies the input to `out` as it is read, byte for byte, so that lines
+/// that can't be parsed are kept too. Writing stops with a warning on the
+/// first error, e.g. when the reader of a pipe exits, without affecting the
+/// processing of the input.
+pub struct Tee<R> {
+    input: R,
+    out: Option<Box<dyn Write + Send>>,
+}
+
+impl<R: Read> Tee<R> {
+    pub fn new(input: R, out: Option<Box<dyn Write + Send>>) -> Self {
+        Tee { input, out }
+    }
+}
+
+impl<R: Read> Read for Tee<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.input.read(buf)?;
+        if let Some(out) = &mut self.out {
+            if let Err(e) = out.write_all(&buf[..n]).and_then(|()| out.flush()) {
+                eprintln!("Could not tee the input, no longer copying it: {}", e);
+                self.out = None;
+            }
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,244 @@
+use crate::throughput::Progress;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use influxdb::WriteQuery;
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Share of the progress, at the start and at the end, during which the
+// adaptive strategy writes more often
+const ADAPTIVE_EDGE: f64 = 0.1;
+const ADAPTIVE_SPEEDUP: u32 = 5;
+
+/// How often status points are written, restic prints several per second
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// One every --interval seconds
+    Time,
+    /// One every --throttle-percent of progress
+    Percent,
+    /// Like time, but five times more often in the first and last 10%
+    Adaptive,
+    /// Every status
+    None,
+}
+
+/// A strategy for one measurement, or for all of them, parsed from
+/// `[MEASUREMENT=]STRATEGY`
+#[derive(Clone, Debug)]
+pub struct ThrottleSpec {
+    measurement: Option<String>,
+    strategy: Strategy,
+}
+
+pub fn parse_spec(s: &str) -> Result<ThrottleSpec, String> {
+    let (measurement, strategy) = match s.split_once('=') {
+        Some((measurement, strategy)) => (Some(measurement.to_string()), strategy),
+        None => (None, s),
+    };
+    Ok(ThrottleSpec {
+        measurement,
+        strategy: Strategy::from_str(strategy, true)?,
+    })
+}
+
+/// Decides which status messages of a measurement are written. The latest
+/// status that wasn't is kept, to be written before the summary.
+pub struct Throttle {
+    strategy: Strategy,
+    interval: Duration,
+    percent: f64,
+    last_time: Option<DateTime<Utc>>,
+    last_step: Option<i64>,
+    held: Option<(WriteQuery, Progress)>,
+}
+
+impl Throttle {
+    fn new(strategy: Strategy, interval: Duration, percent: f64) -> Self {
+        Throttle {
+            strategy,
+            interval,
+            percent,
+            last_time: None,
+            last_step: None,
+            held: None,
+        }
+    }
+
+    /// Whether a status at `time` and `percent_done` (between 0 and 1) is
+    /// written, the first one always is
+    pub fn allow(&mut self, time: DateTime<Utc>, percent_done: f64) -> bool {
+        let step = (percent_done * 100.0 / self.percent.max(f64::MIN_POSITIVE)).floor() as i64;
+        let interval = match self.strategy {
+            Strategy::Adaptive
+                if !(ADAPTIVE_EDGE..=1.0 - ADAPTIVE_EDGE).contains(&percent_done) =>
+            {
+                self.interval / ADAPTIVE_SPEEDUP
+            }
+            _ => self.interval,
+        };
+        let allow = match (self.strategy, self.last_time, self.last_step) {
+            (Strategy::None, _, _) | (_, None, _) => true,
+            (Strategy::Percent, _, Some(last_step)) => step > last_step,
+            (_, Some(last_time), _) => time >= last_time + interval,
+        };
+        if allow {
+            self.last_time = Some(time);
+            self.last_step = Some(step);
+        }
+        allow
+    }
+
+    /// Keeps the latest status that wasn't written
+    pub fn hold(&mut self, query: WriteQuery, progress: Progress) {
+        self.held = Some((query, progress));
+    }
+
+    /// Takes the status kept since the last one written
+    pub fn take_held(&mut self) -> Option<(WriteQuery, Progress)> {
+        self.held.take()
+    }
+}
+
+/// The throttle of each status measurement
+pub struct Throttles {
+    specs: Vec<ThrottleSpec>,
+    interval: Duration,
+    percent: f64,
+    throttles: HashMap<&'static str, Throttle>,
+}
+
+impl Throttles {
+    pub fn new(specs: &[ThrottleSpec], interval: Duration, percent: f64) -> Self {
+        Throttles {
+            specs: specs.to_vec(),
+            interval,
+            percent,
+            throttles: HashMap::new(),
+        }
+    }
+
+    // The last matching spec wins, a measurement's over the default one
+    fn strategy(&self, measurement: &str) -> Strategy {
+        let specific = self
+            .specs
+            .iter()
+            .rfind(|spec| spec.measurement.as_deref() == Some(measurement));
+        let default = self.specs.iter().rfind(|spec| spec.measurement.is_none());
+        specific
+            .or(default)
+            .map_or(Strategy::Time, |spec| spec.strategy)
+    }
+
+    pub fn get(&mut self, measurement: &'static str) -> &mut Throttle {
+        let strategy = self.strategy(measurement);
+        let (interval, percent) = (self.interval, self.percent);
+        self.throttles
+            .entry(measurement)
+            .or_insert_with(|| Throttle::new(strategy, interval, percent))
+    }
+
+    /// Statuses kept by every throttle, to be written before a summary
+    pub fn take_held(&mut self) -> Vec<(WriteQuery, Progress)> {
+        self.throttles
+            .values_mut()
+            .filter_map(Throttle::take_held)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+    use influxdb::{InfluxDbWriteable, Query, Timestamp};
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::UNIX_EPOCH + TimeDelta::seconds(seconds)
+    }
+
+    fn status(files_done: u64) -> (WriteQuery, Progress) {
+        let query = Timestamp::Seconds(1)
+            .into_query("status_message")
+            .add_field("files_done", files_done);
+        let progress = Progress {
+            seconds_elapsed: 0,
+            bytes_done: 0,
+            files_done,
+            total_bytes: 0,
+        };
+        (query, progress)
+    }
+
+    #[test]
+    fn writes_one_status_per_percent_step() {
+        let mut throttle = Throttle::new(Strategy::Percent, Duration::from_secs(10), 10.0);
+        assert!(throttle.allow(at(0), 0.0));
+        assert!(!throttle.allow(at(1), 0.05));
+        assert!(!throttle.allow(at(2), 0.099));
+        assert!(throttle.allow(at(3), 0.1));
+        assert!(!throttle.allow(at(4), 0.15));
+        // jumping several steps writes once
+        assert!(throttle.allow(at(5), 0.5));
+        assert!(!throttle.allow(at(6), 0.55));
+    }
+
+    #[test]
+    fn writes_the_final_step_of_a_clamped_percent_done() {
+        let mut throttle = Throttle::new(Strategy::Percent, Duration::from_secs(10), 10.0);
+        assert!(throttle.allow(at(0), 0.95));
+        // restic reports more than 100% when files grow, clamped to 1.0
+        assert!(throttle.allow(at(1), 1.000004_f64.clamp(0.0, 1.0)));
+        assert!(!throttle.allow(at(2), 1.0));
+    }
+
+    #[test]
+    fn writes_more_often_at_the_adaptive_edges() {
+        let mut throttle = Throttle::new(Strategy::Adaptive, Duration::from_secs(10), 5.0);
+        assert!(throttle.allow(at(0), 0.0));
+        assert!(!throttle.allow(at(1), 0.01));
+        assert!(throttle.allow(at(2), 0.05));
+        // from 10% to 90% the interval is the full one
+        assert!(!throttle.allow(at(4), 0.1));
+        assert!(!throttle.allow(at(11), 0.5));
+        assert!(throttle.allow(at(12), 0.9));
+        assert!(!throttle.allow(at(13), 0.95));
+        assert!(throttle.allow(at(14), 0.95));
+    }
+
+    #[test]
+    fn prefers_the_measurement_spec_over_the_default() {
+        let specs: Vec<ThrottleSpec> = ["restore_status_message=none", "percent", "time"]
+            .iter()
+            .map(|spec| parse_spec(spec).unwrap())
+            .collect();
+        let throttles = Throttles::new(&specs, Duration::from_secs(10), 5.0);
+        assert_eq!(throttles.strategy("restore_status_message"), Strategy::None);
+        // the last default spec wins
+        assert_eq!(throttles.strategy("status_message"), Strategy::Time);
+        assert_eq!(
+            Throttles::new(&[], Duration::from_secs(10), 5.0).strategy("status_message"),
+            Strategy::Time
+        );
+    }
+
+    #[test]
+    fn takes_the_latest_held_status_once() {
+        let mut throttles = Throttles::new(&[], Duration::from_secs(10), 5.0);
+        let throttle = throttles.get("status_message");
+        let (query, progress) = status(1);
+        throttle.hold(query, progress);
+        let (query, progress) = status(2);
+        throttle.hold(query, progress);
+        throttles.get("restore_status_message");
+
+        let held = throttles.take_held();
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].1.files_done, 2);
+        assert_eq!(
+            held[0].0.build().unwrap().get(),
+            "status_message files_done=2i 1"
+        );
+        assert!(throttles.take_held().is_empty());
+    }
+}
@@ -0,0 +1,87 @@
+use influxdb::WriteQuery;
+
+// Weight of the latest rate in the smoothed rate used for the ETA
+const SMOOTHING: f64 = 0.3;
+
+/// Progress reported by a status message
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub seconds_elapsed: u64,
+    pub bytes_done: u64,
+    pub files_done: u64,
+    pub total_bytes: u64,
+}
+
+/// Rates derived from successive status points. The instantaneous rates are
+/// measured since the previous status point, the averages since the start.
+#[derive(Debug, Default)]
+pub struct Rates {
+    pub bytes_per_second: Option<f64>,
+    pub files_per_second: Option<f64>,
+    pub average_bytes_per_second: Option<f64>,
+    pub average_files_per_second: Option<f64>,
+    pub eta_seconds: Option<f64>,
+}
+
+/// Tracks the progress of a backup or restore, whose `seconds_remaining` is
+/// meaningless until restic has scanned most of the files.
+#[derive(Debug, Default)]
+pub struct Throughput {
+    previous: Option<Progress>,
+    smoothed_bytes_per_second: Option<f64>,
+}
+
+impl Throughput {
+    pub fn update(&mut self, progress: Progress) -> Rates {
+        let mut rates = Rates::default();
+        if progress.seconds_elapsed > 0 {
+            let elapsed = progress.seconds_elapsed as f64;
+            rates.average_bytes_per_second = Some(progress.bytes_done as f64 / elapsed);
+            rates.average_files_per_second = Some(progress.files_done as f64 / elapsed);
+        }
+        if let Some(previous) = self.previous {
+            // restic counts whole seconds, and a new run starts over
+            if progress.seconds_elapsed > previous.seconds_elapsed {
+                let elapsed = (progress.seconds_elapsed - previous.seconds_elapsed) as f64;
+                let bytes = progress.bytes_done.saturating_sub(previous.bytes_done);
+                let files = progress.files_done.saturating_sub(previous.files_done);
+                rates.bytes_per_second = Some(bytes as f64 / elapsed);
+                rates.files_per_second = Some(files as f64 / elapsed);
+            } else if progress.seconds_elapsed < previous.seconds_elapsed {
+                self.smoothed_bytes_per_second = None;
+            }
+        }
+        self.previous = Some(progress);
+
+        let smoothed = match (self.smoothed_bytes_per_second, rates.bytes_per_second) {
+            (Some(smoothed), Some(rate)) => Some(smoothed + SMOOTHING * (rate - smoothed)),
+            (None, rate) => rate.or(rates.average_bytes_per_second),
+            (smoothed, None) => smoothed,
+        };
+        self.smoothed_bytes_per_second = smoothed;
+        if let Some(rate) = smoothed.filter(|rate| *rate > 0.0) {
+            let remaining = progress.total_bytes.saturating_sub(progress.bytes_done);
+            rates.eta_seconds = Some(remaining as f64 / rate);
+        }
+        rates
+    }
+}
+
+impl Rates {
+    /// Adds the rates that could be computed to a status point
+    pub fn add_to(self, mut query: WriteQuery) -> WriteQuery {
+        let fields = [
+            ("bytes_per_second", self.bytes_per_second),
+            ("files_per_second", self.files_per_second),
+            ("average_bytes_per_second", self.average_bytes_per_second),
+            ("average_files_per_second", self.average_files_per_second),
+            ("eta_seconds", self.eta_seconds),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                query = query.add_field(field, value);
+            }
+        }
+        query
+    }
+}
@@ -0,0 +1,165 @@
+use crate::Cli;
+use reqwest::{Certificate, Identity};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::ServerName;
+use rustls_pemfile::Item;
+use serde::{Deserialize, Deserializer};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// SHA-256 fingerprint of a certificate, in the colon-separated hex that
+/// `openssl x509 -noout -fingerprint -sha256` prints
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pin([u8; 32]);
+
+impl Pin {
+    fn of(der: &[u8]) -> Self {
+        let digest = ring::digest::digest(&ring::digest::SHA256, der);
+        let mut pin = [0; 32];
+        pin.copy_from_slice(digest.as_ref());
+        Pin(pin)
+    }
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex: Vec<String> = self.0.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(f, "{}", hex.join(":"))
+    }
+}
+
+/// Parses a fingerprint, with or without colons, in either case
+pub fn parse_pin(s: &str) -> Result<Pin, String> {
+    let hex: Vec<char> = s.chars().filter(|c| *c != ':').collect();
+    if hex.len() != 64 {
+        return Err("expected the 64 hex digits of a SHA-256 fingerprint".to_string());
+    }
+    let mut pin = [0; 32];
+    for (byte, digits) in pin.iter_mut().zip(hex.chunks(2)) {
+        let digits: String = digits.iter().collect();
+        *byte = u8::from_str_radix(&digits, 16).map_err(|_| format!("invalid hex `{}`", digits))?;
+    }
+    Ok(Pin(pin))
+}
+
+impl<'de> Deserialize<'de> for Pin {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        parse_pin(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+// Trusts the servers whose certificate is pinned, whoever issued it and
+// whatever names it holds, e.g. a self-signed one
+struct PinnedVerifier(Vec<Pin>);
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let pin = Pin::of(&end_entity.0);
+        if self.0.contains(&pin) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(format!(
+                "the server certificate {} is not pinned",
+                pin
+            )))
+        }
+    }
+}
+
+// The client certificate chain and its key in one PEM buffer, as rustls wants
+// them, and their paths for errors
+struct ClientIdentity {
+    pem: Vec<u8>,
+    paths: String,
+}
+
+fn client_identity(cli: &Cli) -> Result<Option<ClientIdentity>, Box<dyn Error>> {
+    match (&cli.client_cert, &cli.client_key) {
+        (Some(cert), Some(key)) => {
+            let mut pem = read(key)?;
+            pem.push(b'\n');
+            pem.extend(read(cert)?);
+            let paths = format!("{} and {}", cert.display(), key.display());
+            Ok(Some(ClientIdentity { pem, paths }))
+        }
+        (None, None) => Ok(None),
+        // only possible from the config file, clap checks the command line
+        _ => Err("client_cert and client_key go together".into()),
+    }
+}
+
+// reqwest can't verify certificates in another way, the whole TLS
+// configuration is built here
+fn pinned_tls(
+    pins: &[Pin],
+    identity: Option<ClientIdentity>,
+) -> Result<rustls::ClientConfig, Box<dyn Error>> {
+    let builder = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(PinnedVerifier(pins.to_vec())));
+    let Some(ClientIdentity { pem, paths }) = identity else {
+        return Ok(builder.with_no_client_auth());
+    };
+    let mut certificates = Vec::new();
+    let mut key = None;
+    for item in rustls_pemfile::read_all(&mut pem.as_slice())? {
+        match item {
+            Item::X509Certificate(der) => certificates.push(rustls::Certificate(der)),
+            Item::PKCS8Key(der) | Item::RSAKey(der) | Item::ECKey(der) => {
+                key = key.or(Some(rustls::PrivateKey(der)))
+            }
+            _ => {}
+        }
+    }
+    let key = key.ok_or_else(|| format!("{}: no private key", paths))?;
+    let config = builder
+        .with_client_auth_cert(certificates, key)
+        .map_err(|e| format!("{}: {}", paths, e))?;
+    Ok(config)
+}
+
+/// HTTP client shared by the outputs, configured with the TLS options
+pub fn http_client(cli: &Cli) -> Result<reqwest::Client, Box<dyn Error>> {
+    let mut builder = reqwest::Client::builder();
+    let identity = client_identity(cli)?;
+    if !cli.pin_sha256.is_empty() {
+        if cli.ca_cert.is_some() || cli.insecure_skip_verify {
+            return Err("--pin-sha256 replaces --ca-cert and --insecure-skip-verify".into());
+        }
+        let tls = pinned_tls(&cli.pin_sha256, identity)?;
+        return Ok(builder.use_preconfigured_tls(tls).build()?);
+    }
+    if let Some(path) = &cli.ca_cert {
+        // added next to the public roots, a bundle can hold several CAs
+        let certificates = Certificate::from_pem_bundle(&read(path)?)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    if let Some(ClientIdentity { pem, paths }) = identity {
+        let identity = Identity::from_pem(&pem).map_err(|e| format!("{}: {}", paths, e))?;
+        builder = builder.identity(identity);
+    }
+    if cli.insecure_skip_verify {
+        eprintln!("Warning: TLS certificates are not verified (--insecure-skip-verify)");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    Ok(builder.build()?)
+}
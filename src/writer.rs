@@ -0,0 +1,295 @@
+use crate::backend::Backend;
+use crate::error::{Error, Errors};
+use crate::log::{self, verbose, COUNTERS};
+use influxdb::{Query, WriteQuery};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Buffers points and sends them in batches. A batch that still can't be sent
+/// after retrying is appended to the spool file, and the spool is replayed
+/// by the next run while its queue is idle. Unless backend errors abort, failures
+/// don't stop the process: restic keeps running and its output keeps being
+/// consumed.
+pub struct Writer {
+    backend: Backend,
+    errors: Arc<Errors>,
+    pending: Vec<String>,
+    oldest: Option<Instant>,
+    batch_size: usize,
+    flush_interval: Duration,
+    retries: u32,
+    spool: Option<PathBuf>,
+    // points of previous runs not sent yet
+    spooled: Vec<String>,
+}
+
+impl Writer {
+    pub fn new(
+        backend: Backend,
+        errors: Arc<Errors>,
+        batch_size: usize,
+        flush_interval: Duration,
+        retries: u32,
+        spool: Option<PathBuf>,
+    ) -> Self {
+        Writer {
+            backend,
+            errors,
+            pending: Vec::new(),
+            oldest: None,
+            batch_size: batch_size.max(1),
+            flush_interval,
+            retries,
+            spool,
+            spooled: Vec::new(),
+        }
+    }
+
+    /// Default location of the spool file, in the user's cache directory
+    pub fn default_spool() -> Option<PathBuf> {
+        let cache = match std::env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+        };
+        Some(cache.join("restic-to-influxdb").join("spool.txt"))
+    }
+
+    pub async fn push(&mut self, query: WriteQuery) -> Result<(), Error> {
+        // whatever --api and --precision, so that the spool can be sent with
+        // other ones
+        match query.build_with_opts(true) {
+            Ok(line) => self.pending.push(line.get()),
+            Err(e) => return self.errors.handle(Error::Backend(e.to_string())),
+        }
+        let oldest = *self.oldest.get_or_insert_with(Instant::now);
+        if self.pending.len() >= self.batch_size || oldest.elapsed() >= self.flush_interval {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.oldest = None;
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let lines = std::mem::take(&mut self.pending);
+        self.send_or_spool(lines).await
+    }
+
+    // The spool being replayed. It is kept until all its points are sent, so
+    // that they aren't lost when the run is killed, and sent again by the next
+    // run: InfluxDB overwrites a point with the same series and timestamp.
+    fn replay_path(&self) -> Option<PathBuf> {
+        let mut path = self.spool.clone()?.into_os_string();
+        path.push(".replaying");
+        Some(path.into())
+    }
+
+    /// Takes the points left over by previous runs, to be sent with
+    /// `replay_batch`
+    pub fn load_spool(&mut self) {
+        let (Some(spool), Some(path)) = (self.spool.clone(), self.replay_path()) else {
+            return;
+        };
+        if spool.exists() {
+            // a killed run may have left a spool being replayed
+            let moved = if path.exists() {
+                fs::read(&spool)
+                    .and_then(|content| {
+                        OpenOptions::new()
+                            .append(true)
+                            .open(&path)?
+                            .write_all(&content)
+                    })
+                    .and_then(|()| fs::remove_file(&spool))
+            } else {
+                fs::rename(&spool, &path)
+            };
+            if let Err(e) = moved {
+                eprintln!("Could not move spool file {}: {}", spool.display(), e);
+                return;
+            }
+        }
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => return,
+        };
+        self.spooled = decode(&content);
+        verbose!("Replaying {} spooled points", self.spooled.len());
+        self.replayed();
+    }
+
+    // Removes the spool being replayed once all its points are sent
+    fn replayed(&self) {
+        if let (false, Some(path)) = (self.replaying(), self.replay_path()) {
+            if let Err(e) = fs::remove_file(&path) {
+                eprintln!("Could not remove spool file {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    pub fn replaying(&self) -> bool {
+        !self.spooled.is_empty()
+    }
+
+    /// Sends the next batch of spooled points. When it can't be sent, the
+    /// spool is kept as it is for the next run, without trying the other
+    /// batches.
+    pub async fn replay_batch(&mut self) -> Result<(), Error> {
+        let end = self.batch_size.min(self.spooled.len());
+        let batch: Vec<String> = self.spooled.drain(..end).collect();
+        match self.send(&batch).await {
+            Ok(()) => {
+                self.replayed();
+                Ok(())
+            }
+            Err(e) => {
+                let left = batch.len() + std::mem::take(&mut self.spooled).len();
+                if let Some(path) = self.replay_path() {
+                    eprintln!("Kept {} spooled points in {}", left, path.display());
+                }
+                self.errors.handle(Error::Backend(e))
+            }
+        }
+    }
+
+    async fn send_or_spool(&mut self, lines: Vec<String>) -> Result<(), Error> {
+        match self.send(&lines).await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                self.spool(&lines);
+                self.errors.handle(Error::Backend(e))
+            }
+        }
+    }
+
+    // Sends a batch, retrying with exponential backoff
+    async fn send(&self, lines: &[String]) -> Result<(), String> {
+        let mut delay = Duration::from_secs(1);
+        let mut attempt = 0;
+        loop {
+            let start = Instant::now();
+            match self.backend.send(lines).await {
+                Ok(()) => {
+                    verbose!("Wrote {} points in {:?}", lines.len(), start.elapsed());
+                    log::count(&COUNTERS.points_written, lines.len() as u64);
+                    return Ok(());
+                }
+                Err(e) if attempt < self.retries => {
+                    if self.errors.warn_backend() {
+                        eprintln!("Write failed ({}), retrying in {:?}", e, delay);
+                    }
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+
+    fn spool(&self, lines: &[String]) {
+        let Some(path) = &self.spool else {
+            eprintln!("No spool file, dropping {} points", lines.len());
+            log::count(&COUNTERS.points_dropped, lines.len() as u64);
+            return;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| OpenOptions::new().create(true).append(true).open(path))
+            .and_then(|mut file| {
+                // string fields can hold newlines, each point is written as
+                // a JSON string
+                for line in lines {
+                    serde_json::to_writer(&mut file, line)?;
+                    writeln!(file)?;
+                }
+                Ok(())
+            });
+        match result {
+            Ok(()) => {
+                eprintln!("Spooled {} points to {}", lines.len(), path.display());
+                log::count(&COUNTERS.points_spooled, lines.len() as u64);
+            }
+            Err(e) => {
+                eprintln!(
+                    "Could not spool to {} ({}), dropping {} points",
+                    path.display(),
+                    e,
+                    lines.len()
+                );
+                log::count(&COUNTERS.points_dropped, lines.len() as u64);
+            }
+        }
+    }
+}
+
+// The points of a spool file, one JSON string per line. Spools of earlier
+// versions hold the line protocol as is.
+fn decode(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|_| line.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Precision, V2Client};
+    use crate::error::Policy;
+    use influxdb::Timestamp;
+    use std::path::Path;
+
+    fn writer(spool: &Path) -> Writer {
+        let backend = Backend::V2(V2Client::new(
+            reqwest::Client::new(),
+            "http://localhost:1",
+            "org".to_string(),
+            "bucket".to_string(),
+            "token".to_string(),
+            Precision::Ns,
+        ));
+        let policy = Policy::Warn;
+        let errors = Arc::new(Errors::new(policy, policy, policy, policy));
+        let spool = Some(spool.to_path_buf());
+        Writer::new(backend, errors, 100, Duration::from_secs(1), 0, spool)
+    }
+
+    #[test]
+    fn round_trips_a_multi_line_field_through_the_spool() {
+        let path = std::env::temp_dir().join(format!(
+            "restic-to-influxdb-spool-{}.txt",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        let line = WriteQuery::new(Timestamp::Nanoseconds(1), "run_result")
+            .add_field("exit_code", 12)
+            .add_field("stderr", "Fatal: unable to save snapshot\nexit 12")
+            .build_with_opts(true)
+            .unwrap()
+            .get();
+        let lines = vec![line, "m v=1u 2".to_string()];
+
+        writer(&path).spool(&lines);
+        let mut replay = writer(&path);
+        replay.load_spool();
+        assert_eq!(std::mem::take(&mut replay.spooled), lines);
+        assert!(!path.exists());
+        fs::remove_file(replay.replay_path().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reads_spools_of_earlier_versions() {
+        assert_eq!(
+            decode("m v=1i 1\n\n\"m v=2i 2\"\n"),
+            vec!["m v=1i 1".to_string(), "m v=2i 2".to_string()]
+        );
+    }
+}
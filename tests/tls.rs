@@ -0,0 +1,249 @@
+//! Writes to a local HTTPS server that requires a client certificate, with
+//! a CA, a server certificate and a client certificate generated by rcgen.
+
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+use std::path::PathBuf;
+use std::process::{Output, Stdio};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
+use tokio_rustls::rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+const SUMMARY: &str = r#"{"message_type":"summary","files_new":1,"snapshot_id":"abc"}"#;
+
+struct Pki {
+    dir: PathBuf,
+    // SHA-256 fingerprints, as openssl prints them
+    server_fingerprint: String,
+    ca_fingerprint: String,
+    server_config: Arc<ServerConfig>,
+}
+
+impl Drop for Pki {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn fingerprint(der: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, der);
+    let hex: Vec<String> = digest
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect();
+    hex.join(":")
+}
+
+// A CA, a server certificate for localhost and a client certificate, in PEM
+// files in a directory of its own
+fn pki(name: &str) -> Pki {
+    let dir = std::env::temp_dir().join(format!(
+        "restic-to-influxdb-tls-{}-{}",
+        name,
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "restic-to-influxdb test CA");
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+
+    let server_key = KeyPair::generate().unwrap();
+    let server = CertificateParams::new(vec!["localhost".to_string()])
+        .unwrap()
+        .signed_by(&server_key, &ca, &ca_key)
+        .unwrap();
+
+    let client_key = KeyPair::generate().unwrap();
+    let mut client_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    client_params
+        .distinguished_name
+        .push(DnType::CommonName, "client");
+    let client = client_params.signed_by(&client_key, &ca, &ca_key).unwrap();
+
+    std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+    std::fs::write(dir.join("client.pem"), client.pem()).unwrap();
+    std::fs::write(dir.join("client.key"), client_key.serialize_pem()).unwrap();
+
+    let mut roots = RootCertStore::empty();
+    roots.add(&Certificate(ca.der().to_vec())).unwrap();
+    let server_config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+        .with_single_cert(
+            vec![Certificate(server.der().to_vec())],
+            PrivateKey(server_key.serialize_der()),
+        )
+        .unwrap();
+
+    Pki {
+        dir,
+        server_fingerprint: fingerprint(server.der()),
+        ca_fingerprint: fingerprint(ca.der()),
+        server_config: Arc::new(server_config),
+    }
+}
+
+// Answers every write with 204 and passes on its body. Returns the port.
+async fn serve(config: Arc<ServerConfig>) -> (u16, mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let acceptor = TlsAcceptor::from(config);
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                // a rejected client fails the handshake
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                let (head, length) = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    assert!(n > 0, "connection closed in the request headers");
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let length = text[..end]
+                            .lines()
+                            .find_map(|line| {
+                                let (name, value) = line.split_once(':')?;
+                                name.eq_ignore_ascii_case("content-length")
+                                    .then(|| value.trim().parse::<usize>().unwrap())
+                            })
+                            .unwrap_or(0);
+                        break (end + 4, length);
+                    }
+                };
+                while request.len() < head + length {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let body = String::from_utf8_lossy(&request[head..head + length]);
+                tx.send(body.to_string()).unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                    .await
+                    .unwrap();
+                stream.shutdown().await.unwrap();
+            });
+        }
+    });
+    (port, rx)
+}
+
+// Runs restic-to-influxdb on a summary, aborting on the first failed write
+async fn run(pki: &Pki, port: u16, args: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_restic-to-influxdb"))
+        .env("XDG_CONFIG_HOME", &pki.dir)
+        .args(["--host", &format!("https://localhost:{}", port)])
+        .args(["--database", "restic", "--user", "u", "--password", "p"])
+        .args(["--retries", "0", "--no-spool"])
+        .args(["--on-backend-error", "abort"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(SUMMARY.as_bytes()).await.unwrap();
+    drop(stdin);
+    child.wait_with_output().await.unwrap()
+}
+
+fn path(pki: &Pki, file: &str) -> String {
+    pki.dir.join(file).display().to_string()
+}
+
+fn client_certificate(pki: &Pki) -> [String; 4] {
+    [
+        "--client-cert".to_string(),
+        path(pki, "client.pem"),
+        "--client-key".to_string(),
+        path(pki, "client.key"),
+    ]
+}
+
+#[tokio::test]
+async fn writes_with_the_ca_and_a_client_certificate() {
+    let pki = pki("ca");
+    let (port, mut bodies) = serve(pki.server_config.clone()).await;
+    let ca = path(&pki, "ca.pem");
+    let mut args = vec!["--ca-cert", &ca];
+    let client = client_certificate(&pki);
+    args.extend(client.iter().map(String::as_str));
+    let output = run(&pki, port, &args).await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let body = bodies.recv().await.unwrap();
+    assert!(body.starts_with("summary_message,"), "{}", body);
+    assert!(body.contains("snapshot_id=\"abc\""), "{}", body);
+}
+
+#[tokio::test]
+async fn fails_without_the_ca() {
+    let pki = pki("no-ca");
+    let (port, mut bodies) = serve(pki.server_config.clone()).await;
+    let client = client_certificate(&pki);
+    let args: Vec<&str> = client.iter().map(String::as_str).collect();
+    let output = run(&pki, port, &args).await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("UnknownIssuer"));
+    assert!(bodies.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn fails_without_a_client_certificate() {
+    let pki = pki("no-client");
+    let (port, mut bodies) = serve(pki.server_config.clone()).await;
+    let ca = path(&pki, "ca.pem");
+    let output = run(&pki, port, &["--ca-cert", &ca]).await;
+    assert!(!output.status.success());
+    assert!(bodies.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn writes_to_a_pinned_certificate() {
+    let pki = pki("pin");
+    let (port, mut bodies) = serve(pki.server_config.clone()).await;
+    let mut args = vec!["--pin-sha256", &pki.server_fingerprint];
+    let client = client_certificate(&pki);
+    args.extend(client.iter().map(String::as_str));
+    let output = run(&pki, port, &args).await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(bodies.recv().await.unwrap().starts_with("summary_message,"));
+}
+
+#[tokio::test]
+async fn fails_with_another_pinned_certificate() {
+    let pki = pki("wrong-pin");
+    let (port, mut bodies) = serve(pki.server_config.clone()).await;
+    let mut args = vec!["--pin-sha256", &pki.ca_fingerprint];
+    let client = client_certificate(&pki);
+    args.extend(client.iter().map(String::as_str));
+    let output = run(&pki, port, &args).await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not pinned"));
+    assert!(bodies.try_recv().is_err());
+}